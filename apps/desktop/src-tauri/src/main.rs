@@ -1,53 +1,414 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::borrow::Cow;
+use std::net::TcpListener;
 use std::process::{Command, Child};
 use std::path::PathBuf;
-use tauri::{AppHandle, Manager};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use clap::Parser;
+use rand::RngCore;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Developer overrides for the backend launch, parsed from the command line.
+#[derive(Parser)]
+#[command(name = "CaseAI", about = "CaseAI desktop shell")]
+struct Cli {
+    /// Path to an `lcai_api` build to use instead of the bundled resource binary.
+    #[arg(long)]
+    backend_path: Option<PathBuf>,
+
+    /// Port to have the backend bind, instead of an OS-assigned one.
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// JWT secret to hand the backend, instead of a freshly generated one.
+    #[arg(long)]
+    jwt_secret: Option<String>,
+
+    /// Don't spawn a backend process; attach to one already running.
+    #[arg(long)]
+    no_spawn: bool,
+}
+
+/// How long we wait for the backend to exit on its own after asking it to
+/// shut down before we fall back to a hard kill.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long we'll wait for the backend to report healthy before giving up
+/// and telling the frontend it failed to start.
+const READINESS_DEADLINE: Duration = Duration::from_secs(30);
+const READINESS_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const READINESS_MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+/// How often the watchdog checks whether the backend process is still alive.
+const WATCHDOG_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Restart budget: more than this many restarts inside `RESTART_WINDOW` is
+/// treated as a crash loop rather than a one-off fault.
+const MAX_RESTARTS: usize = 3;
+const RESTART_WINDOW: Duration = Duration::from_secs(60);
+
+/// Authorities reachable through the `lcai://` proxy (i.e. `lcai://api/...`
+/// requests); anything else is rejected outright. This alone doesn't
+/// restrict *which* backend routes are reachable through `api` — with no
+/// frontend API surface defined in this tree yet, there's nothing real to
+/// enumerate. Once the frontend's routes are known, extend `proxy_to_backend`
+/// with a `(Method, path prefix)` allow-list rather than forwarding every
+/// path verbatim.
+const ALLOWED_HOSTS: &[&str] = &["api"];
+
+/// Per-launch backend connection info, managed as Tauri state so command
+/// handlers and the frontend never have to assume a fixed port or secret.
+struct BackendConfig {
+    port: u16,
+    jwt_secret: String,
+}
+
+fn is_executable(path: &std::path::Path) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .map(|meta| meta.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    }
+    #[cfg(not(unix))]
+    {
+        true
+    }
+}
+
+/// Reads `LCAI_BACKEND_OVERRIDE`, validating that it points at an executable
+/// file so a typo'd path fails loudly here instead of as a confusing spawn
+/// error later.
+fn backend_override_from_env() -> Option<PathBuf> {
+    let path = PathBuf::from(std::env::var_os("LCAI_BACKEND_OVERRIDE")?);
+    if is_executable(&path) {
+        Some(path)
+    } else {
+        eprintln!("LCAI_BACKEND_OVERRIDE set but not a valid executable: {:?}", path);
+        None
+    }
+}
 
 fn backend_exe_path(app: &AppHandle) -> PathBuf {
+    if let Some(path) = app.state::<Cli>().backend_path.clone() {
+        println!("Using backend from --backend-path: {:?}", path);
+        return path;
+    }
+
+    if let Some(path) = backend_override_from_env() {
+        println!("Using backend from LCAI_BACKEND_OVERRIDE: {:?}", path);
+        return path;
+    }
+
     let resource_dir = app.path().resource_dir().expect("resource_dir");
     resource_dir.join("bin").join("lcai_api.exe")
 }
 
-fn spawn_backend(app: &AppHandle) -> Option<Child> {
+/// Binds to an OS-assigned localhost port and immediately releases it.
+///
+/// There's a small window between releasing the listener and the backend
+/// binding the same port (TOCTOU); we accept that risk for the MVP rather
+/// than handing the bound socket to the child process.
+fn allocate_port() -> std::io::Result<u16> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    listener.local_addr().map(|addr| addr.port())
+}
+
+fn generate_jwt_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn spawn_backend(app: &AppHandle, config: &BackendConfig) -> Option<Child> {
     let exe = backend_exe_path(app);
     if !exe.exists() {
         eprintln!("Backend exe not found at: {:?}", exe);
         return None;
     }
 
-    // Bind only to localhost. Port is fixed for MVP (8787).
-    // In later phases we can randomize a free port and pass via env var.
     let mut cmd = Command::new(exe);
-    cmd.env("LCAI_PORT", "8787");
-    cmd.env("LCAI_JWT_SECRET", "CHANGE_ME_DEV_ONLY");
+    cmd.env("LCAI_PORT", config.port.to_string());
+    cmd.env("LCAI_JWT_SECRET", &config.jwt_secret);
     cmd.spawn().ok()
 }
 
+#[tauri::command]
+fn backend_port(config: tauri::State<BackendConfig>) -> u16 {
+    config.port
+}
+
+/// Asks the backend to shut down cleanly over HTTP, then polls until it
+/// exits or `timeout` elapses, at which point we fall back to a hard kill.
+///
+/// Await this off the caller's thread (see `on_window_event`) so a slow or
+/// unresponsive backend can't freeze the window's event loop while we wait
+/// on it; the caller is responsible for closing the window/exiting once
+/// this resolves, since teardown must not race ahead of it.
+async fn graceful_shutdown(app: &AppHandle, port: u16, jwt_secret: &str, timeout: Duration) {
+    let url = format!("http://127.0.0.1:{port}/shutdown");
+    let _ = reqwest::Client::new()
+        .post(&url)
+        .bearer_auth(jwt_secret)
+        .timeout(Duration::from_secs(1))
+        .send()
+        .await;
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        let exited = {
+            let backend_child = app.state::<Mutex<BackendChild>>();
+            let mut backend_child = backend_child.lock().unwrap();
+            match backend_child.child.as_mut() {
+                Some(child) => matches!(child.try_wait(), Ok(Some(_))),
+                None => true,
+            }
+        };
+
+        if exited {
+            return;
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+        tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+    }
+
+    let backend_child = app.state::<Mutex<BackendChild>>();
+    if let Some(child) = &mut backend_child.lock().unwrap().child {
+        let _ = child.kill();
+    }
+}
+
+/// Polls the backend's health endpoint with exponential backoff until it
+/// answers or `READINESS_DEADLINE` elapses. The main window stays hidden
+/// (see `setup`) until this resolves, so the frontend never sees a
+/// connection-refused error on cold start.
+fn watch_backend_readiness(app: AppHandle, port: u16) {
+    tauri::async_runtime::spawn(async move {
+        let client = reqwest::Client::new();
+        let url = format!("http://127.0.0.1:{port}/health");
+        let deadline = Instant::now() + READINESS_DEADLINE;
+        let mut backoff = READINESS_INITIAL_BACKOFF;
+
+        loop {
+            if let Ok(resp) = client.get(&url).send().await {
+                if resp.status().is_success() {
+                    if let Some(window) = app.get_webview_window("main") {
+                        let _ = window.show();
+                    }
+                    return;
+                }
+            }
+
+            if Instant::now() >= deadline {
+                if let Some(window) = app.get_webview_window("main") {
+                    let _ = window.show();
+                }
+                let _ = app.emit("backend-start-failed", ());
+                return;
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(READINESS_MAX_BACKOFF);
+        }
+    });
+}
+
+/// Periodically checks whether the backend process is still running and,
+/// if it exited without us asking it to (see `BackendChild::shutdown_initiated`),
+/// respawns it on the same port/secret. Stops watching once the app itself
+/// is shutting down, or once restarts exceed `MAX_RESTARTS` within
+/// `RESTART_WINDOW`, in which case the frontend is told it's fatal.
+fn watch_backend_process(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut restart_times: Vec<Instant> = Vec::new();
+
+        loop {
+            tokio::time::sleep(WATCHDOG_POLL_INTERVAL).await;
+
+            let exited = {
+                let backend_child = app.state::<Mutex<BackendChild>>();
+                let mut backend_child = backend_child.lock().unwrap();
+                if backend_child.shutdown_initiated.load(Ordering::SeqCst) {
+                    return;
+                }
+                // `None` means the initial spawn or a previous restart attempt
+                // failed to spawn at all; treat that as exited too so we keep retrying (and
+                // counting against MAX_RESTARTS) instead of watching nothing.
+                match backend_child.child.as_mut() {
+                    Some(child) => matches!(child.try_wait(), Ok(Some(_))),
+                    None => true,
+                }
+            };
+
+            if !exited {
+                continue;
+            }
+
+            let now = Instant::now();
+            restart_times.retain(|t| now.duration_since(*t) < RESTART_WINDOW);
+            if restart_times.len() >= MAX_RESTARTS {
+                let _ = app.emit("backend-crash-loop", ());
+                return;
+            }
+            restart_times.push(now);
+
+            let port = app.state::<BackendConfig>().port;
+            let new_child = spawn_backend(&app, &app.state::<BackendConfig>());
+
+            {
+                let backend_child = app.state::<Mutex<BackendChild>>();
+                backend_child.lock().unwrap().child = new_child;
+            }
+
+            let _ = app.emit("backend-restarted", ());
+            watch_backend_readiness(app.clone(), port);
+        }
+    });
+}
+
+/// Forwards an `lcai://api/...` request to the backend on its localhost
+/// port, injecting the session JWT. This keeps the real port and secret out
+/// of frontend JavaScript and gives the webview a stable origin that
+/// survives port randomization.
+async fn proxy_to_backend(
+    app: &AppHandle,
+    request: tauri::http::Request<Vec<u8>>,
+) -> tauri::http::Response<Cow<'static, [u8]>> {
+    let host = request.uri().host().unwrap_or_default();
+    if !ALLOWED_HOSTS.contains(&host) {
+        return tauri::http::Response::builder()
+            .status(tauri::http::StatusCode::FORBIDDEN)
+            .body(Cow::Borrowed(&[][..]))
+            .unwrap();
+    }
+
+    let (port, jwt_secret) = {
+        let config = app.state::<BackendConfig>();
+        (config.port, config.jwt_secret.clone())
+    };
+    let path_and_query = request
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    let url = format!("http://127.0.0.1:{port}{path_and_query}");
+
+    let response = reqwest::Client::new()
+        .request(request.method().clone(), &url)
+        .bearer_auth(jwt_secret)
+        .body(request.into_body())
+        .send()
+        .await;
+
+    match response {
+        Ok(resp) => {
+            let status = resp.status().as_u16();
+            let body = resp.bytes().await.unwrap_or_default().to_vec();
+            tauri::http::Response::builder()
+                .status(status)
+                .body(Cow::Owned(body))
+                .unwrap()
+        }
+        Err(_) => tauri::http::Response::builder()
+            .status(tauri::http::StatusCode::BAD_GATEWAY)
+            .body(Cow::Borrowed(&[][..]))
+            .unwrap(),
+    }
+}
+
 fn main() {
+    let cli = Cli::parse();
+
     tauri::Builder::default()
-        .setup(|app| {
-            // Start backend
+        .register_asynchronous_uri_scheme_protocol("lcai", |ctx, request, responder| {
+            let app = ctx.app_handle().clone();
+            tauri::async_runtime::spawn(async move {
+                responder.respond(proxy_to_backend(&app, request).await);
+            });
+        })
+        .setup(move |app| {
+            // Start backend. The main window is created hidden (see
+            // tauri.conf.json) and only shown once `watch_backend_readiness`
+            // confirms the backend is actually answering requests.
             let handle = app.handle().clone();
-            let child = spawn_backend(&handle);
+
+            let port = match cli.port {
+                Some(port) => port,
+                None => allocate_port().expect("failed to allocate a free localhost port"),
+            };
+            let jwt_secret = cli.jwt_secret.clone().unwrap_or_else(generate_jwt_secret);
+            let no_spawn = cli.no_spawn;
+            let config = BackendConfig { port, jwt_secret };
+
+            app.manage(cli);
+            app.manage(config);
+
+            let child = if no_spawn {
+                None
+            } else {
+                spawn_backend(&handle, &app.state::<BackendConfig>())
+            };
 
             // Store child handle for shutdown
-            app.manage(BackendChild(child));
+            app.manage(Mutex::new(BackendChild {
+                child,
+                shutdown_initiated: Arc::new(AtomicBool::new(false)),
+            }));
+
+            watch_backend_readiness(handle.clone(), port);
+            if !no_spawn {
+                watch_backend_process(handle);
+            }
             Ok(())
         })
+        .invoke_handler(tauri::generate_handler![backend_port])
         .on_window_event(|window, event| {
-            // No-op; could add behavior later.
-            let _ = (window, event);
+            if let tauri::WindowEvent::CloseRequested { api, .. } = event {
+                // Hold the close: graceful_shutdown must actually finish
+                // before we let the process tear down, or it races the
+                // backend's shutdown request and hands-on-kill fallback.
+                api.prevent_close();
+
+                let app = window.app_handle().clone();
+                let (port, jwt_secret) = {
+                    let config = app.state::<BackendConfig>();
+                    (config.port, config.jwt_secret.clone())
+                };
+                app.state::<Mutex<BackendChild>>()
+                    .lock()
+                    .unwrap()
+                    .shutdown_initiated
+                    .store(true, Ordering::SeqCst);
+
+                tauri::async_runtime::spawn(async move {
+                    graceful_shutdown(&app, port, &jwt_secret, SHUTDOWN_TIMEOUT).await;
+                    app.exit(0);
+                });
+            }
         })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
-struct BackendChild(Option<Child>);
+struct BackendChild {
+    child: Option<Child>,
+    /// Set once we've asked the backend to shut down on purpose, so other
+    /// subsystems can tell a clean exit apart from a crash.
+    shutdown_initiated: Arc<AtomicBool>,
+}
 
 impl Drop for BackendChild {
     fn drop(&mut self) {
-        if let Some(child) = &mut self.0 {
+        if let Some(child) = &mut self.child {
             let _ = child.kill();
         }
     }